@@ -5,24 +5,23 @@
 //! A term is the fundamental unit of operation of the PubGrub algorithm.
 //! It is a positive or negative expression regarding a set of versions.
 
-use crate::range::Range;
-use crate::version::Version;
+use crate::version_set::VersionSet;
 
 ///  A positive or negative expression regarding a set of versions.
 #[derive(Debug, Clone, Eq, PartialEq)]
-pub enum Term<V: Clone + Ord + Version> {
+pub enum Term<VS: VersionSet> {
     /// For example, "1.0.0 <= v < 2.0.0" is a positive expression
     /// that is evaluated true if a version is selected
     /// and comprised between version 1.0.0 and version 2.0.0.
-    Positive(Range<V>),
+    Positive(VS),
     /// The term "not v < 3.0.0" is a negative expression
     /// that is evaluated true if a version is selected >= 3.0.0
     /// or if no version is selected at all.
-    Negative(Range<V>),
+    Negative(VS),
 }
 
 // Base methods.
-impl<V: Clone + Ord + Version> Term<V> {
+impl<VS: VersionSet> Term<VS> {
     /// Simply check if a term is positive.
     pub fn is_positive(&self) -> bool {
         match self {
@@ -36,13 +35,13 @@ impl<V: Clone + Ord + Version> Term<V> {
     /// the opposite of the evaluation of the original one.
     pub fn negate(&self) -> Self {
         match self {
-            Self::Positive(range) => Self::Negative(range.clone()),
-            Self::Negative(range) => Self::Positive(range.clone()),
+            Self::Positive(set) => Self::Negative(set.clone()),
+            Self::Negative(set) => Self::Positive(set.clone()),
         }
     }
 
     /// Evaluate a term regarding a given choice (or absence) of version.
-    pub fn accept_optional_version(&self, v_option: &Option<V>) -> bool {
+    pub fn accept_optional_version(&self, v_option: &Option<VS::V>) -> bool {
         match (self, v_option) {
             (Self::Negative(_), None) => true,
             (Self::Positive(_), None) => false,
@@ -51,54 +50,108 @@ impl<V: Clone + Ord + Version> Term<V> {
     }
 
     /// Evaluate a term regarding a given choice of version.
-    pub fn accept_version(&self, v: &V) -> bool {
+    pub fn accept_version(&self, v: &VS::V) -> bool {
         match self {
-            Self::Positive(range) => range.contains(v),
-            Self::Negative(range) => !(range.contains(v)),
+            Self::Positive(set) => set.contains(v),
+            Self::Negative(set) => !(set.contains(v)),
         }
     }
 }
 
 // Set operations with terms.
-impl<'a, V: 'a + Clone + Ord + Version> Term<V> {
+impl<VS: VersionSet> Term<VS> {
     /// Compute the intersection of two terms.
     /// If at least one term is positive, the intersection is also positive.
-    pub fn intersection(&self, other: &Term<V>) -> Term<V> {
+    pub fn intersection(&self, other: &Term<VS>) -> Term<VS> {
         match (self, other) {
-            (Self::Positive(r1), Self::Positive(r2)) => Self::Positive(r1.intersection(r2)),
-            (Self::Positive(r1), Self::Negative(r2)) => {
-                Self::Positive(r1.intersection(&r2.negate()))
+            (Self::Positive(s1), Self::Positive(s2)) => Self::Positive(s1.intersection(s2)),
+            (Self::Positive(s1), Self::Negative(s2)) => {
+                Self::Positive(s1.intersection(&s2.complement()))
             }
-            (Self::Negative(r1), Self::Positive(r2)) => {
-                Self::Positive(r1.negate().intersection(r2))
+            (Self::Negative(s1), Self::Positive(s2)) => {
+                Self::Positive(s1.complement().intersection(s2))
             }
-            (Self::Negative(r1), Self::Negative(r2)) => Self::Negative(r1.union(r2)),
+            (Self::Negative(s1), Self::Negative(s2)) => Self::Negative(s1.union(s2)),
         }
     }
 
     /// Compute the union of two terms.
     /// If at least one term is negative, the union is also negative.
-    pub fn union(&self, other: &Term<V>) -> Term<V> {
+    pub fn union(&self, other: &Term<VS>) -> Term<VS> {
         (self.negate().intersection(&other.negate())).negate()
     }
 
+    /// Intersect with another term in place.
+    /// When `self` is positive, its set is updated via
+    /// [VersionSet::intersection_assign] instead of allocating a new one.
+    /// When `self` is negative, a new set is built and replaces it.
+    pub fn intersection_mut(&mut self, other: &Term<VS>) {
+        match other {
+            Self::Positive(s2) => match self {
+                Self::Positive(s1) => s1.intersection_assign(s2),
+                Self::Negative(s1) => {
+                    let mut set = s1.complement();
+                    set.intersection_assign(s2);
+                    *self = Self::Positive(set);
+                }
+            },
+            Self::Negative(s2) => match self {
+                Self::Positive(s1) => s1.intersection_assign(&s2.complement()),
+                Self::Negative(s1) => *self = Self::Negative(s1.union(s2)),
+            },
+        }
+    }
+
     /// Compute the intersection of multiple terms.
-    pub fn intersect_all<T: AsRef<Term<V>>>(
+    /// Stops as soon as the accumulator reaches `Positive(empty)`, since
+    /// that is the absorbing element of intersection and no further term
+    /// can change the result, without pulling an extra term from the
+    /// iterator to notice it.
+    pub fn intersect_all<T: AsRef<Term<VS>>>(
         mut all_terms: impl Iterator<Item = T>,
-    ) -> Option<Term<V>> {
-        all_terms.next().map(|initial_term| {
-            all_terms.fold(initial_term.as_ref().clone(), |acc, term| {
-                acc.intersection(term.as_ref())
-            })
-        })
+    ) -> Option<Term<VS>> {
+        let mut acc = all_terms.next()?.as_ref().clone();
+        while acc != Self::Positive(VS::empty()) {
+            match all_terms.next() {
+                Some(term) => acc.intersection_mut(term.as_ref()),
+                None => break,
+            }
+        }
+        Some(acc)
     }
 
     /// Indicate if this term is a subset of another term.
     /// Just like for sets, we say that t1 is a subset of t2
     /// if and only if t1 ∩ t2 = t1.
-    pub fn subset_of(&self, other: &Term<V>) -> bool {
+    pub fn subset_of(&self, other: &Term<VS>) -> bool {
         self == &self.intersection(other)
     }
+
+    /// Return the indices of a minimal subset of `terms` whose intersection
+    /// is still `Positive(empty)`, i.e. a minimal unsatisfiable core.
+    /// The result is deterministic. Returns `None` if `terms` is not
+    /// actually unsatisfiable.
+    pub fn minimal_unsat_core<T: AsRef<Term<VS>>>(terms: &[T]) -> Option<Vec<usize>> {
+        if Self::intersect_all(terms.iter()) != Some(Self::Positive(VS::empty())) {
+            return None;
+        }
+        let mut kept: Vec<usize> = (0..terms.len()).collect();
+        let mut i = 0;
+        while i < kept.len() {
+            // Tentatively drop the term at `i`; keep it dropped if the rest
+            // is still unsatisfiable, otherwise restore it and move on.
+            let removed = kept.remove(i);
+            let still_unsat = Self::intersect_all(kept.iter().map(|&idx| terms[idx].as_ref()))
+                == Some(Self::Positive(VS::empty()));
+            if still_unsat {
+                // `removed` was not needed for the core: leave it out.
+            } else {
+                kept.insert(i, removed);
+                i += 1;
+            }
+        }
+        Some(kept)
+    }
 }
 
 /// Describe a relation between a set of terms S and another term t.
@@ -117,15 +170,15 @@ pub enum Relation {
 }
 
 // Relation between terms.
-impl<'a, V: 'a + Clone + Ord + Version> Term<V> {
+impl<'a, VS: 'a + VersionSet> Term<VS> {
     /// Check if a set of terms satisfies this term.
     ///
     /// We say that a set of terms S "satisfies" a term t
     /// if t must be true whenever every term in S is true.
-    pub fn satisfied_by(&self, terms: impl Iterator<Item = &'a Term<V>>) -> bool {
+    pub fn satisfied_by(&self, terms: impl Iterator<Item = &'a Term<VS>>) -> bool {
         match Self::intersect_all(terms) {
-            // Negative(Range::none) is always evaluated true.
-            None => *self == Self::Negative(Range::none()),
+            // Negative(empty) is always evaluated true.
+            None => *self == Self::Negative(VS::empty()),
             Some(intersection) => intersection.subset_of(self),
         }
     }
@@ -134,36 +187,225 @@ impl<'a, V: 'a + Clone + Ord + Version> Term<V> {
     ///
     /// We say that a set of terms S "contradicts" a term t
     /// if t must be false whenever every term in S is true.
-    pub fn contradicted_by(&self, terms: impl Iterator<Item = &'a Term<V>>) -> bool {
-        match Self::intersect_all(terms) {
-            // Positive(Range::none) is always evaluated false.
-            None => *self == Self::Positive(Range::none()),
-            Some(intersection) => intersection.intersection(self) == Self::Positive(Range::none()),
+    ///
+    /// Stops as soon as the running intersection with `self` reaches
+    /// `Positive(empty)`, without consuming the rest of `terms`.
+    pub fn contradicted_by(&self, terms: impl Iterator<Item = &'a Term<VS>>) -> bool {
+        let mut terms = terms;
+        let mut acc = match terms.next() {
+            None => return *self == Self::Positive(VS::empty()),
+            Some(first) => first.clone(),
+        };
+        if acc.intersection(self) == Self::Positive(VS::empty()) {
+            return true;
         }
+        for term in terms {
+            acc.intersection_mut(term);
+            if acc.intersection(self) == Self::Positive(VS::empty()) {
+                return true;
+            }
+        }
+        false
     }
 
     /// Check if a set of terms satisfies or contradicts a given term.
     /// Otherwise the relation is inconclusive.
-    pub fn relation_with<T: AsRef<Term<V>>>(
+    pub fn relation_with<T: AsRef<Term<VS>>>(
         &self,
         other_terms: Option<impl Iterator<Item = T>>,
     ) -> Relation {
+        self.relation_with_witness(other_terms).0
+    }
+
+    /// Same as [Term::relation_with], but also returns an example version
+    /// accepted by `other_terms` that demonstrates the relation: one that
+    /// `self` rejects for `Contradicted`, or that `self` doesn't already
+    /// force for `Inconclusive`. `Satisfied` carries no witness.
+    pub fn relation_with_witness<T: AsRef<Term<VS>>>(
+        &self,
+        other_terms: Option<impl Iterator<Item = T>>,
+    ) -> (Relation, Option<VS::V>) {
         let other_terms_intersection = other_terms
             .and_then(|ot| Self::intersect_all(ot))
-            .unwrap_or(Self::Negative(Range::none()));
+            .unwrap_or(Self::Negative(VS::empty()));
         let full_intersection = self.intersection(&other_terms_intersection);
         if full_intersection == other_terms_intersection {
-            Relation::Satisfied
-        } else if full_intersection == Self::Positive(Range::none()) {
-            Relation::Contradicted
+            (Relation::Satisfied, None)
         } else {
-            Relation::Inconclusive
+            let witness =
+                Self::witness(&other_terms_intersection.intersection(&self.negate()));
+            if full_intersection == Self::Positive(VS::empty()) {
+                (Relation::Contradicted, witness)
+            } else {
+                (Relation::Inconclusive, witness)
+            }
+        }
+    }
+
+    /// An arbitrary version accepted by `term`, if any.
+    fn witness(term: &Term<VS>) -> Option<VS::V> {
+        match term {
+            Self::Positive(set) => set.some_element(),
+            Self::Negative(set) => set.complement().some_element(),
         }
     }
 }
 
-impl<V: Clone + Ord + Version> AsRef<Term<V>> for Term<V> {
-    fn as_ref(&self) -> &Term<V> {
-        &self
+impl<VS: VersionSet> AsRef<Term<VS>> for Term<VS> {
+    fn as_ref(&self) -> &Term<VS> {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::fmt;
+    use std::rc::Rc;
+
+    /// A tiny bitset-backed `VersionSet` over versions `0..8`, used only to
+    /// exercise `Term`'s algorithms without a real `Range`/`Version` impl.
+    #[derive(Debug, Clone, Eq, PartialEq)]
+    struct BitSet(u8);
+
+    impl fmt::Display for BitSet {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{:#010b}", self.0)
+        }
+    }
+
+    impl VersionSet for BitSet {
+        type V = u8;
+
+        fn empty() -> Self {
+            BitSet(0)
+        }
+        fn singleton(v: u8) -> Self {
+            BitSet(1 << v)
+        }
+        fn complement(&self) -> Self {
+            BitSet(!self.0)
+        }
+        fn intersection(&self, other: &Self) -> Self {
+            BitSet(self.0 & other.0)
+        }
+        fn contains(&self, v: &u8) -> bool {
+            self.0 & (1 << v) != 0
+        }
+        fn some_element(&self) -> Option<u8> {
+            (0..8).find(|v| self.contains(v))
+        }
+    }
+
+    fn pos(bits: u8) -> Term<BitSet> {
+        Term::Positive(BitSet(bits))
+    }
+    fn neg(bits: u8) -> Term<BitSet> {
+        Term::Negative(BitSet(bits))
+    }
+
+    #[test]
+    fn relation_with_witness_satisfied_has_no_witness() {
+        let self_term = pos(0b0000_1111);
+        let others = [pos(0b0000_0011)];
+        let (relation, witness) = self_term.relation_with_witness(Some(others.iter()));
+        assert!(matches!(relation, Relation::Satisfied));
+        assert_eq!(witness, None);
+    }
+
+    #[test]
+    fn relation_with_witness_contradicted_returns_version_self_rejects() {
+        let self_term = neg(0b0000_0011); // rejects versions 0 and 1
+        let others = [pos(0b0000_0001)]; // only accepts version 0
+        let (relation, witness) = self_term.relation_with_witness(Some(others.iter()));
+        assert!(matches!(relation, Relation::Contradicted));
+        let v = witness.unwrap();
+        assert!(!self_term.accept_version(&v));
+        assert!(others[0].accept_version(&v));
+    }
+
+    #[test]
+    fn relation_with_witness_inconclusive_returns_undecided_version() {
+        let self_term = pos(0b0000_0001); // only accepts version 0
+        let others = [pos(0b0000_0011)]; // accepts versions 0 and 1
+        let (relation, witness) = self_term.relation_with_witness(Some(others.iter()));
+        assert!(matches!(relation, Relation::Inconclusive));
+        let v = witness.unwrap();
+        assert!(!self_term.accept_version(&v));
+        assert!(others[0].accept_version(&v));
+    }
+
+    #[test]
+    fn minimal_unsat_core_drops_redundant_wide_term() {
+        // {0} ∩ {1} ∩ everything is unsatisfiable, and the last term is
+        // redundant for that: it should be dropped from the core.
+        let terms = [pos(0b0000_0001), pos(0b0000_0010), pos(0b1111_1111)];
+        assert_eq!(Term::minimal_unsat_core(&terms), Some(vec![0, 1]));
+    }
+
+    #[test]
+    fn minimal_unsat_core_none_when_satisfiable() {
+        let terms = [pos(0b0000_0011), pos(0b0000_0010)];
+        assert_eq!(Term::minimal_unsat_core(&terms), None);
+    }
+
+    /// Wraps a slice iterator and counts how many items were pulled from
+    /// it, so tests can assert that short-circuiting code stops early
+    /// instead of draining the whole iterator.
+    struct CountingIter<'a> {
+        inner: std::slice::Iter<'a, Term<BitSet>>,
+        pulls: Rc<Cell<usize>>,
     }
-}
\ No newline at end of file
+
+    impl<'a> Iterator for CountingIter<'a> {
+        type Item = &'a Term<BitSet>;
+        fn next(&mut self) -> Option<Self::Item> {
+            self.pulls.set(self.pulls.get() + 1);
+            self.inner.next()
+        }
+    }
+
+    #[test]
+    fn intersect_all_does_not_drain_iterator_past_absorbing_element() {
+        let terms = [
+            pos(0b0000_0001),
+            pos(0b0000_0010),
+            pos(0b1111_1111),
+            pos(0b1111_1111),
+        ];
+        let pulls = Rc::new(Cell::new(0));
+        let iter = CountingIter {
+            inner: terms.iter(),
+            pulls: Rc::clone(&pulls),
+        };
+        assert_eq!(Term::intersect_all(iter), Some(pos(0b0000_0000)));
+        assert!(
+            pulls.get() < terms.len(),
+            "iterator was drained past the absorbing element: {} pulls",
+            pulls.get()
+        );
+    }
+
+    #[test]
+    fn contradicted_by_does_not_drain_iterator_past_absorbing_element() {
+        let terms = [
+            pos(0b1111_1111),
+            pos(0b1111_1110),
+            pos(0b1111_1111),
+            pos(0b1111_1111),
+        ];
+        let pulls = Rc::new(Cell::new(0));
+        let iter = CountingIter {
+            inner: terms.iter(),
+            pulls: Rc::clone(&pulls),
+        };
+        let self_term = pos(0b0000_0001);
+        assert!(self_term.contradicted_by(iter));
+        assert!(
+            pulls.get() < terms.len(),
+            "iterator was drained past the absorbing element: {} pulls",
+            pulls.get()
+        );
+    }
+}
@@ -0,0 +1,123 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! As its name suggests, the `VersionSet` trait describes sets of versions.
+//!
+//! One obvious requirement is for version sets to be able to
+//! represent any negation or union of themselves.
+//! Providing set operations is what is needed to then be able to navigate
+//! the solution space and nail down exactly which versions are acceptable.
+//!
+//! [Range](crate::range::Range) is the default implementation of a `VersionSet`,
+//! backed by a union of half-open intervals.
+//! But other implementations exist, for example for
+//! version sets that are easier modelled by disjoint intervals
+//! or by a dimension such as pre-releases.
+
+use std::fmt::{Debug, Display};
+
+use crate::range::Range;
+use crate::version::Version;
+
+/// A set of versions of a given package.
+pub trait VersionSet: Debug + Display + Clone + Eq {
+    /// Version type associated with the sets manipulated.
+    type V: Debug + Display + Clone + Eq;
+
+    // Constructors
+
+    /// Constructor for an empty set containing no version.
+    fn empty() -> Self;
+    /// Constructor for a set containing exactly one version.
+    fn singleton(v: Self::V) -> Self;
+
+    // Operations
+
+    /// Compute the complement of this set.
+    fn complement(&self) -> Self;
+    /// Compute the intersection with another set.
+    fn intersection(&self, other: &Self) -> Self;
+    /// Intersect with another set in place.
+    ///
+    /// The default implementation is just `*self = self.intersection(other)`,
+    /// but implementations backed by a mutable internal representation
+    /// (e.g. [Range]'s segment list) should override this to update that
+    /// representation directly instead of allocating a new one.
+    fn intersection_assign(&mut self, other: &Self) {
+        *self = self.intersection(other);
+    }
+    /// Evaluate membership of a version in this set.
+    fn contains(&self, v: &Self::V) -> bool;
+    /// If the set is not empty, return an arbitrary version it contains.
+    ///
+    /// There's no required relationship between the versions returned by
+    /// two calls to this method, even on equal sets: implementations are
+    /// free to return whichever member is cheapest to produce.
+    fn some_element(&self) -> Option<Self::V>;
+
+    // Automatically implemented functions
+
+    /// Constructor for the set containing all versions.
+    /// Automatically implemented as `Self::empty().complement()`.
+    fn full() -> Self {
+        Self::empty().complement()
+    }
+
+    /// Compute the union with another set.
+    /// Automatically implemented as `!(!self & !other)`.
+    fn union(&self, other: &Self) -> Self {
+        self.complement()
+            .intersection(&other.complement())
+            .complement()
+    }
+}
+
+/// Implementation of [VersionSet] for the default [Range] type.
+impl<V: Debug + Display + Clone + Eq + Ord + Version> VersionSet for Range<V> {
+    type V = V;
+
+    fn empty() -> Self {
+        Range::none()
+    }
+
+    fn singleton(v: Self::V) -> Self {
+        Range::exact(v)
+    }
+
+    fn complement(&self) -> Self {
+        self.negate()
+    }
+
+    fn intersection(&self, other: &Self) -> Self {
+        Range::intersection(self, other)
+    }
+
+    fn intersection_assign(&mut self, other: &Self) {
+        // Updates the segment list in place rather than building a new one.
+        Range::intersection_assign(self, other)
+    }
+
+    fn contains(&self, v: &Self::V) -> bool {
+        Range::contains(self, v)
+    }
+
+    fn some_element(&self) -> Option<Self::V> {
+        // Derive a member from the lower bound of the lowest segment: the
+        // bound itself if included, the next version if excluded, and the
+        // lowest version if unbounded.
+        self.bounding_range().map(|(start, _)| match start {
+            std::ops::Bound::Included(v) => v.clone(),
+            std::ops::Bound::Excluded(v) => v.bump(),
+            std::ops::Bound::Unbounded => V::lowest(),
+        })
+    }
+
+    fn full() -> Self {
+        Range::full()
+    }
+
+    fn union(&self, other: &Self) -> Self {
+        Range::union(self, other)
+    }
+}